@@ -1,47 +1,250 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::borrow::Cow;
+use std::net::IpAddr;
 
 use validator::{HasLen, Validate, ValidateArgs, ValidationError};
-// use validator::{validation::ip::validate_ip, HasLen};
 
 lazy_static! {
     // Regex from the specs
     // https://html.spec.whatwg.org/multipage/forms.html#valid-e-mail-address
     // It will mark esoteric email addresses like quoted string as invalid
     static ref EMAIL_USER_RE: Regex = Regex::new(r"^(?i)[a-z0-9.!#$%&'*+/=?^_`{|}~-]+\z").unwrap();
+
+    // SMTP literal domain, e.g. `[127.0.0.1]` or `[::1]`
+    static ref EMAIL_DOMAIN_LITERAL_RE: Regex = Regex::new(r"^\[([A-Fa-f0-9:\.]+)\]\z").unwrap();
+
+    static ref EMAIL_DOMAIN_RE: Regex = Regex::new(
+        r"^[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?(?:\.[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?)*$"
+    ).unwrap();
 }
 
-#[must_use]
-pub fn validate_username<'a, T>(val: T) -> bool
+// Lets callers validate &str, String, Cow<str>, or Option<T> uniformly.
+pub trait ValidateUsername {
+    fn validate_username(&self) -> bool;
+}
+
+impl ValidateUsername for str {
+    fn validate_username(&self) -> bool {
+        validate_username_detailed(self).is_ok()
+    }
+}
+
+impl ValidateUsername for &str {
+    fn validate_username(&self) -> bool {
+        (*self).validate_username()
+    }
+}
+
+impl ValidateUsername for String {
+    fn validate_username(&self) -> bool {
+        self.as_str().validate_username()
+    }
+}
+
+impl<'a> ValidateUsername for Cow<'a, str> {
+    fn validate_username(&self) -> bool {
+        self.as_ref().validate_username()
+    }
+}
+
+impl<T: ValidateUsername> ValidateUsername for Option<T> {
+    fn validate_username(&self) -> bool {
+        match self {
+            Some(val) => val.validate_username(),
+            None => true,
+        }
+    }
+}
+
+// Same as validate_username, but returns why it failed: "empty",
+// "too_long", or "invalid_char".
+pub fn validate_username_detailed<'a, T>(val: T) -> Result<(), ValidationError>
 where
     T: Into<Cow<'a, str>>,
 {
     let val = val.into();
+
     if val.is_empty() {
-        return false;
+        let mut err = ValidationError::new("empty");
+        err.add_param(Cow::from("length"), &0u64);
+        return Err(err);
     }
 
     // validate the length of each part of the email, BEFORE doing the regex
     // according to RFC5321 the max length of the local part is 64 characters
     // and the max length of the domain part is 255 characters
     // https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.3.1.1
-    if val.length() > 64 {
-        return false;
+    let length = val.length();
+    if length > 64 {
+        let mut err = ValidationError::new("too_long");
+        err.add_param(Cow::from("length"), &length);
+        return Err(err);
     }
 
     if !EMAIL_USER_RE.is_match(&val) {
+        return Err(ValidationError::new("invalid_char"));
+    }
+
+    Ok(())
+}
+
+#[must_use]
+pub fn validate_username<'a, T>(val: T) -> bool
+where
+    T: Into<Cow<'a, str>>,
+{
+    val.into().validate_username()
+}
+
+// Validates a full email address: the local part as above, plus the domain
+// (an SMTP literal like `[127.0.0.1]`, or a punycoded domain name).
+#[must_use]
+pub fn validate_email<'a, T>(val: T) -> bool
+where
+    T: Into<Cow<'a, str>>,
+{
+    let val = val.into();
+    if val.is_empty() || !val.contains('@') {
+        return false;
+    }
+
+    // Split on the *last* `@`: the local part may itself contain `@` when
+    // quoted, but we don't support quoted local parts here.
+    let mut parts = val.rsplitn(2, '@');
+    let domain_part = match parts.next() {
+        Some(domain) => domain,
+        None => return false,
+    };
+    let user_part = match parts.next() {
+        Some(user) => user,
+        None => return false,
+    };
+
+    if !validate_username(user_part) {
         return false;
     }
 
-    true
+    validate_domain(domain_part)
+}
+
+// Which characters a `UsernamePolicy` accepts in a username.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharacterClass {
+    // Same charset as EMAIL_USER_RE, with `.` and `-` as separators.
+    EmailLocalPart,
+    // Letters, digits, and `_`, with `_` as a separator.
+    AlphanumericUnderscore,
+}
+
+impl CharacterClass {
+    fn is_allowed(self, c: char) -> bool {
+        match self {
+            CharacterClass::EmailLocalPart => {
+                c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c)
+            }
+            CharacterClass::AlphanumericUnderscore => c.is_ascii_alphanumeric() || c == '_',
+        }
+    }
+
+    fn is_separator(self, c: char) -> bool {
+        match self {
+            CharacterClass::EmailLocalPart => c == '.' || c == '-',
+            CharacterClass::AlphanumericUnderscore => c == '_',
+        }
+    }
+}
+
+// A configurable set of rules for what counts as a valid username.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsernamePolicy {
+    pub min_length: u64,
+    pub max_length: u64,
+    pub character_class: CharacterClass,
+    pub allow_leading_separator: bool,
+    pub allow_trailing_separator: bool,
+}
+
+impl Default for UsernamePolicy {
+    // Reproduces the behavior of validate_username.
+    fn default() -> Self {
+        UsernamePolicy {
+            min_length: 1,
+            max_length: 64,
+            character_class: CharacterClass::EmailLocalPart,
+            allow_leading_separator: true,
+            allow_trailing_separator: true,
+        }
+    }
+}
+
+impl UsernamePolicy {
+    pub fn validate(&self, name: &str) -> Result<(), ValidationError> {
+        let length = name.length();
+
+        if length < self.min_length {
+            let mut err = ValidationError::new("too_short");
+            err.add_param(Cow::from("min_length"), &self.min_length);
+            err.add_param(Cow::from("actual_length"), &length);
+            return Err(err);
+        }
+
+        if length > self.max_length {
+            let mut err = ValidationError::new("too_long");
+            err.add_param(Cow::from("max_length"), &self.max_length);
+            err.add_param(Cow::from("actual_length"), &length);
+            return Err(err);
+        }
+
+        if let Some(c) = name.chars().find(|c| !self.character_class.is_allowed(*c)) {
+            let mut err = ValidationError::new("invalid_char");
+            err.add_param(Cow::from("char"), &c.to_string());
+            return Err(err);
+        }
+
+        if !self.allow_leading_separator {
+            if let Some(c) = name.chars().next() {
+                if self.character_class.is_separator(c) {
+                    return Err(ValidationError::new("leading_separator"));
+                }
+            }
+        }
+
+        if !self.allow_trailing_separator {
+            if let Some(c) = name.chars().last() {
+                if self.character_class.is_separator(c) {
+                    return Err(ValidationError::new("trailing_separator"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_domain(domain: &str) -> bool {
+    if let Some(captures) = EMAIL_DOMAIN_LITERAL_RE.captures(domain) {
+        return captures[1].parse::<IpAddr>().is_ok();
+    }
+
+    if domain.length() > 255 {
+        return false;
+    }
+
+    match idna::domain_to_ascii(domain) {
+        Ok(ascii_domain) => EMAIL_DOMAIN_RE.is_match(&ascii_domain),
+        Err(_) => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
 
-    use super::validate_username;
+    use super::{
+        validate_email, validate_username, validate_username_detailed, CharacterClass,
+        UsernamePolicy, ValidateUsername,
+    };
 
     #[test]
     fn test_validate_username() {
@@ -95,4 +298,99 @@ mod tests {
         let test = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
         assert_eq!(validate_username(test), false);
     }
+
+    #[test]
+    fn test_validate_username_trait() {
+        assert!("email".validate_username());
+        assert!(!"a\n".validate_username());
+        assert!(String::from("email").validate_username());
+        assert!(!String::from("a\n").validate_username());
+
+        let cow: Cow<'static, str> = "email".into();
+        assert!(cow.validate_username());
+
+        let some: Option<&str> = Some("email");
+        assert!(some.validate_username());
+        let none: Option<&str> = None;
+        assert!(none.validate_username());
+        let invalid: Option<&str> = Some("a\n");
+        assert!(!invalid.validate_username());
+    }
+
+    #[test]
+    fn test_validate_username_detailed() {
+        assert!(validate_username_detailed("email").is_ok());
+        assert_eq!(validate_username_detailed("").unwrap_err().code, "empty");
+        let too_long = "a".repeat(65);
+        assert_eq!(
+            validate_username_detailed(too_long).unwrap_err().code,
+            "too_long"
+        );
+        assert_eq!(
+            validate_username_detailed("a ").unwrap_err().code,
+            "invalid_char"
+        );
+    }
+
+    #[test]
+    fn test_username_policy_default_matches_validate_username() {
+        let policy = UsernamePolicy::default();
+        assert!(policy.validate("email").is_ok());
+        assert!(policy.validate("").is_err());
+        let too_long = "a".repeat(65);
+        assert!(policy.validate(&too_long).is_err());
+    }
+
+    #[test]
+    fn test_username_policy_alphanumeric_underscore() {
+        let policy = UsernamePolicy {
+            min_length: 2,
+            max_length: 20,
+            character_class: CharacterClass::AlphanumericUnderscore,
+            allow_leading_separator: false,
+            allow_trailing_separator: false,
+        };
+
+        assert!(policy.validate("hello_98").is_ok());
+        assert_eq!(
+            policy.validate("Hello-98").unwrap_err().code,
+            "invalid_char"
+        );
+        assert_eq!(policy.validate("a").unwrap_err().code, "too_short");
+        assert_eq!(
+            policy.validate("_hello").unwrap_err().code,
+            "leading_separator"
+        );
+        assert_eq!(
+            policy.validate("hello_").unwrap_err().code,
+            "trailing_separator"
+        );
+    }
+
+    #[test]
+    fn test_validate_email() {
+        let tests = vec![
+            ("email@example.com", true),
+            ("weirder-email@example.com", true),
+            ("email@[127.0.0.1]", true),
+            ("email@[2001:db8::1]", true),
+            ("email@あいうえお.com", true),
+            ("", false),
+            ("abc@", false),
+            ("something@", false),
+            ("email", false),
+            ("email@[256.0.0.1]", false),
+            ("email@-example.com", false),
+            ("email@junk[127.0.0.1]", false),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                validate_email(input),
+                expected,
+                "Email `{}` was not classified correctly",
+                input
+            );
+        }
+    }
 }